@@ -0,0 +1,81 @@
+use postgres;
+
+error_chain! {
+    foreign_links {
+        Postgres(postgres::error::Error);
+        R2D2(::r2d2::Error);
+    }
+
+    errors {
+        /// A unique constraint was violated - e.g. a vertex or edge that
+        /// already exists was inserted again. Callers should treat this
+        /// the same as a normal "already exists" result, rather than a
+        /// real failure.
+        UniqueViolation {
+            description("a uniqueness constraint was violated")
+            display("a uniqueness constraint was violated")
+        }
+
+        /// The database reported a transient failure - a serialization
+        /// failure or a detected deadlock - rather than a permanent one.
+        /// Callers can safely retry the transaction that produced this.
+        Retryable(sql_state: String) {
+            description("a retryable postgres error occurred")
+            display("a retryable postgres error occurred (sqlstate {})", sql_state)
+        }
+    }
+}
+
+/// Classifies a postgres driver error by its five-character SQLSTATE code.
+///
+/// Returns `Some(ErrorKind)` for the SQLSTATE classes IndraDB knows how to
+/// react to specially - unique violations and serialization/deadlock
+/// failures. Returns `None` for everything else (including errors that
+/// didn't originate from the database at all, e.g. a dropped connection),
+/// in which case callers should propagate `err` as-is.
+pub fn classify_db_error(err: &postgres::error::Error) -> Option<ErrorKind> {
+    let db_error = err.as_db()?;
+    classify_sqlstate(db_error.code.code())
+}
+
+/// Maps a five-character SQLSTATE code onto the `ErrorKind` IndraDB
+/// reacts to specially, if any. Split out from `classify_db_error` so the
+/// mapping itself can be unit tested without needing a real database
+/// error to unwrap it from.
+fn classify_sqlstate(code: &str) -> Option<ErrorKind> {
+    match code {
+        "23505" => Some(ErrorKind::UniqueViolation),
+        "40001" | "40P01" => Some(ErrorKind::Retryable(code.to_string())),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify_sqlstate, ErrorKind};
+
+    #[test]
+    fn classifies_unique_violations() {
+        match classify_sqlstate("23505") {
+            Some(ErrorKind::UniqueViolation) => (),
+            other => panic!("expected UniqueViolation, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn classifies_serialization_and_deadlock_failures_as_retryable() {
+        for code in &["40001", "40P01"] {
+            match classify_sqlstate(code) {
+                Some(ErrorKind::Retryable(ref sql_state)) if sql_state == code => (),
+                other => panic!("expected Retryable({:?}), got {:?}", code, other),
+            }
+        }
+    }
+
+    #[test]
+    fn does_not_classify_other_sqlstates() {
+        for code in &["23503", "08006", "42601", ""] {
+            assert!(classify_sqlstate(code).is_none(), "unexpectedly classified {:?}", code);
+        }
+    }
+}
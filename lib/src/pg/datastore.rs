@@ -1,22 +1,122 @@
-use super::schema;
+use super::migrations;
 use super::super::{Datastore, EdgeDirection, EdgeQuery, Transaction, VertexQuery};
 use super::util::CTEQueryBuilder;
 use chrono::DateTime;
 use chrono::offset::Utc;
-use errors::{Error, Result};
+use errors::{classify_db_error, Error, ErrorKind, Result};
 use models;
 use num_cpus;
 use postgres;
+use postgres::tls::native_tls::NativeTls;
 use postgres::types::ToSql;
 use r2d2::{Pool, PooledConnection};
-use r2d2_postgres::{PostgresConnectionManager, TlsMode};
+use r2d2_postgres::{PostgresConnectionManager, TlsMode as PoolTlsMode};
 use serde_json::Value as JsonValue;
+use std::cell::RefCell;
 use std::cmp::min;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
 use std::i64;
 use std::mem;
 use util::generate_uuid_v1;
 use uuid::Uuid;
 
+/// The TLS policy to use when connecting to postgres.
+///
+/// This mirrors `postgres::TlsMode`/`r2d2_postgres::TlsMode`, but is a
+/// plain, `Clone`-able value rather than an enum carrying a boxed
+/// negotiator, so the same value can be passed to both
+/// `PostgresDatastore::new_with_tls_mode` and `run_migrations`; each of
+/// them builds its own negotiator from it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostgresTlsMode {
+    /// Never use TLS.
+    None,
+    /// Use TLS if the server supports it, otherwise fall back to an
+    /// unencrypted connection.
+    Prefer,
+    /// Require TLS; fail to connect if the server doesn't support it.
+    Require,
+}
+
+impl PostgresTlsMode {
+    /// Parses the `sslmode` query parameter out of a postgres connection
+    /// string, the same way libpq does, so that a connection string alone
+    /// is enough to pick the right behavior if `tls_mode` isn't specified
+    /// explicitly. Unrecognized or missing `sslmode` values default to
+    /// `Prefer`, matching libpq's own default.
+    fn from_connection_string(connection_string: &str) -> Self {
+        for (key, value) in Self::parse_params(connection_string) {
+            if key.eq_ignore_ascii_case("sslmode") {
+                return match &value[..] {
+                    "disable" => PostgresTlsMode::None,
+                    "require" | "verify-ca" | "verify-full" => PostgresTlsMode::Require,
+                    // "allow" means "try non-SSL first, then fall back to
+                    // SSL" - it still permits TLS, so of our three modes
+                    // `Prefer` is the closest match, not `None`.
+                    _ => PostgresTlsMode::Prefer,
+                };
+            }
+        }
+
+        PostgresTlsMode::Prefer
+    }
+
+    /// Parses `key=value` pairs out of a postgres connection string.
+    /// libpq accepts two forms, and we need to recognize `sslmode` in
+    /// either: a URI, with parameters after a `?` and joined with `&`
+    /// (`postgres://host/db?sslmode=require`), and a whitespace-separated
+    /// keyword/value list (`host=localhost sslmode=require`).
+    fn parse_params(connection_string: &str) -> Vec<(String, String)> {
+        let is_uri = connection_string.contains("://");
+
+        let (param_str, separator) = if is_uri {
+            match connection_string.find('?') {
+                Some(i) => (&connection_string[i + 1..], '&'),
+                None => return Vec::new(),
+            }
+        } else {
+            (connection_string, ' ')
+        };
+
+        param_str
+            .split(separator)
+            .filter_map(|pair| {
+                let pair = pair.trim();
+                let mut parts = pair.splitn(2, '=');
+                let key = parts.next()?.trim();
+                let value = parts.next()?.trim().trim_matches('\'').trim_matches('"');
+
+                if key.is_empty() {
+                    None
+                } else {
+                    Some((key.to_string(), value.to_string()))
+                }
+            })
+            .collect()
+    }
+
+    fn negotiator() -> Result<NativeTls> {
+        NativeTls::new().map_err(|err| Error::with_chain(err, "Could not initialize the TLS negotiator"))
+    }
+
+    fn to_pool_tls_mode(&self) -> Result<PoolTlsMode> {
+        match *self {
+            PostgresTlsMode::None => Ok(PoolTlsMode::None),
+            PostgresTlsMode::Prefer => Ok(PoolTlsMode::Prefer(Box::new(Self::negotiator()?))),
+            PostgresTlsMode::Require => Ok(PoolTlsMode::Require(Box::new(Self::negotiator()?))),
+        }
+    }
+
+    fn to_connection_tls_mode(&self) -> Result<postgres::TlsMode> {
+        match *self {
+            PostgresTlsMode::None => Ok(postgres::TlsMode::None),
+            PostgresTlsMode::Prefer => Ok(postgres::TlsMode::Prefer(Box::new(Self::negotiator()?))),
+            PostgresTlsMode::Require => Ok(postgres::TlsMode::Require(Box::new(Self::negotiator()?))),
+        }
+    }
+}
+
 /// A datastore that is backed by a postgres database.
 #[derive(Clone, Debug)]
 pub struct PostgresDatastore {
@@ -31,12 +131,32 @@ impl PostgresDatastore {
     ///   postgres. If `None`, it defaults to twice the number of CPUs.
     /// * `connetion_string` - The postgres database connection string.
     pub fn new(pool_size: Option<u32>, connection_string: String) -> Result<PostgresDatastore> {
+        Self::new_with_tls_mode(pool_size, connection_string, None)
+    }
+
+    /// Creates a new postgres-backed datastore, with explicit control over
+    /// the TLS policy used to connect to postgres.
+    ///
+    /// # Arguments
+    /// * `pool_size` - The maximum number of connections to maintain to
+    ///   postgres. If `None`, it defaults to the number of CPUs, capped at
+    ///   128.
+    /// * `connetion_string` - The postgres database connection string.
+    /// * `tls_mode` - The TLS policy to connect with. If `None`, it's
+    ///   derived from the `sslmode` parameter in `connection_string`, the
+    ///   same way libpq clients behave.
+    pub fn new_with_tls_mode(
+        pool_size: Option<u32>,
+        connection_string: String,
+        tls_mode: Option<PostgresTlsMode>,
+    ) -> Result<PostgresDatastore> {
         let unwrapped_pool_size: u32 = match pool_size {
             Some(val) => val,
             None => min(num_cpus::get() as u32, 128u32),
         };
 
-        let manager = PostgresConnectionManager::new(&*connection_string, TlsMode::None)?;
+        let tls_mode = tls_mode.unwrap_or_else(|| PostgresTlsMode::from_connection_string(&connection_string));
+        let manager = PostgresConnectionManager::new(&*connection_string, tls_mode.to_pool_tls_mode()?)?;
         let pool = Pool::builder()
             .max_size(unwrapped_pool_size)
             .build(manager)?;
@@ -44,19 +164,38 @@ impl PostgresDatastore {
         Ok(PostgresDatastore { pool: pool })
     }
 
-    /// Creates a new postgres-backed datastore.
+    /// Creates the schema, by applying every migration that hasn't yet
+    /// been run.
+    ///
+    /// Unlike the old, one-shot `create_schema` this replaces, this is
+    /// safe to call repeatedly, including against a database that already
+    /// has data in it.
     ///
     /// # Arguments
     /// * `connetion_string` - The postgres database connection string.
+    #[deprecated(note = "renamed to `run_migrations`")]
     pub fn create_schema(connection_string: String) -> Result<()> {
-        let conn = postgres::Connection::connect(connection_string, postgres::TlsMode::None)
-            .map_err(|err| Error::with_chain(err, "Could not connect to the postgres database"))?;
+        Self::run_migrations(connection_string, None)
+    }
 
-        for statement in schema::SCHEMA.split(";") {
-            conn.execute(statement, &vec![])?;
-        }
+    /// Brings the schema up to date, applying every migration with a
+    /// version greater than the highest version already recorded in the
+    /// database's `schema_migrations` table, in order, inside a single
+    /// transaction. This is safe to call repeatedly, including against a
+    /// database that already has data in it, and against a database that
+    /// other IndraDB processes are concurrently migrating.
+    ///
+    /// # Arguments
+    /// * `connetion_string` - The postgres database connection string.
+    /// * `tls_mode` - The TLS policy to connect with. If `None`, it's
+    ///   derived from the `sslmode` parameter in `connection_string`, the
+    ///   same way libpq clients behave.
+    pub fn run_migrations(connection_string: String, tls_mode: Option<PostgresTlsMode>) -> Result<()> {
+        let tls_mode = tls_mode.unwrap_or_else(|| PostgresTlsMode::from_connection_string(&connection_string));
+        let conn = postgres::Connection::connect(connection_string, tls_mode.to_connection_tls_mode()?)
+            .map_err(|err| Error::with_chain(err, "Could not connect to the postgres database"))?;
 
-        Ok(())
+        migrations::run(&conn)
     }
 }
 
@@ -69,12 +208,33 @@ impl Datastore<PostgresTransaction> for PostgresDatastore {
 }
 
 /// A postgres-backed datastore transaction.
-#[derive(Debug)]
+///
+/// Field order matters here: Rust drops struct fields in declaration
+/// order, and both `statement_cache` and `trans` unsafely borrow `conn`
+/// (see the transmutes in `new`/`prepare_cached`), so `conn` must be
+/// declared - and therefore dropped - last. Otherwise the pooled
+/// connection would be returned to the pool (and potentially handed to
+/// another thread) while cached statements/the transaction still hold a
+/// dangling reference to it.
 pub struct PostgresTransaction {
+    // Keyed on the stable SQL text `CTEQueryBuilder` produces (i.e. before
+    // parameter values are bound), so that repeated queries of the same
+    // shape - especially the fixed metadata/count statements - are parsed
+    // and planned once and reused, rather than on every call.
+    statement_cache: RefCell<HashMap<String, postgres::stmt::Statement<'static>>>,
     trans: postgres::transaction::Transaction<'static>,
     conn: Box<PooledConnection<PostgresConnectionManager>>,
 }
 
+impl fmt::Debug for PostgresTransaction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("PostgresTransaction")
+            .field("trans", &self.trans)
+            .field("conn", &self.conn)
+            .finish()
+    }
+}
+
 impl PostgresTransaction {
     fn new(conn: PooledConnection<PostgresConnectionManager>) -> Result<Self> {
         let conn = Box::new(conn);
@@ -89,9 +249,35 @@ impl PostgresTransaction {
         Ok(PostgresTransaction {
             conn: conn,
             trans: trans,
+            statement_cache: RefCell::new(HashMap::new()),
         })
     }
 
+    /// Prepares `query` the first time it's seen, and reuses the cached
+    /// `Statement` on every subsequent call with the same SQL text.
+    fn prepare_cached(&self, query: &str) -> Result<()> {
+        if !self.statement_cache.borrow().contains_key(query) {
+            let stmt: postgres::stmt::Statement<'static> = unsafe { mem::transmute(self.trans.prepare(query)?) };
+            self.statement_cache.borrow_mut().insert(query.to_string(), stmt);
+        }
+
+        Ok(())
+    }
+
+    /// Runs `query` as a cached prepared statement, binding `params`.
+    fn query_cached(&self, query: &str, params: &[&ToSql]) -> Result<postgres::rows::Rows> {
+        self.prepare_cached(query)?;
+        let cache = self.statement_cache.borrow();
+        Ok(cache.get(query).unwrap().query(params)?)
+    }
+
+    /// Runs `query` as a cached prepared statement, binding `params`.
+    fn execute_cached(&self, query: &str, params: &[&ToSql]) -> Result<u64> {
+        self.prepare_cached(query)?;
+        let cache = self.statement_cache.borrow();
+        Ok(cache.get(query).unwrap().execute(params)?)
+    }
+
     fn vertex_query_to_sql(&self, q: &VertexQuery, sql_query_builder: &mut CTEQueryBuilder) {
         match q {
             &VertexQuery::All {
@@ -221,6 +407,170 @@ impl PostgresTransaction {
             }
         }
     }
+
+    /// The maximum number of bound parameters Postgres allows in a single
+    /// statement. `create_vertices`/`create_edges` split their input into
+    /// `INSERT`s no larger than this, rather than assuming an arbitrarily
+    /// large batch always fits in one round trip.
+    const MAX_BIND_PARAMS: u32 = 65_535;
+
+    /// Creates multiple vertices in a handful of round trips, via
+    /// multi-valued `INSERT`s, rather than one `INSERT` per vertex.
+    ///
+    /// Returns a vector aligned with `vertices`, where each entry is `true`
+    /// if that vertex was newly inserted, and `false` if a vertex with the
+    /// same id already existed (the same semantics as `create_vertex`, just
+    /// batched).
+    pub fn create_vertices(&self, vertices: &[models::Vertex]) -> Result<Vec<bool>> {
+        if vertices.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Because this command could fail, we need to set a savepoint to
+        // roll back to, rather than spoiling the entire transaction
+        let trans = self.trans.savepoint("create_vertices")?;
+
+        let mut inserted_ids: HashSet<Uuid> = HashSet::with_capacity(vertices.len());
+
+        // Postgres caps a single statement at 65535 bound parameters, so a
+        // batch larger than that has to be split across several `INSERT`s -
+        // two bound parameters per vertex, hence the chunk size below.
+        for chunk in vertices.chunks(Self::MAX_BIND_PARAMS as usize / 2) {
+            let mut value_placeholders = Vec::with_capacity(chunk.len());
+            let mut params: Vec<&ToSql> = Vec::with_capacity(chunk.len() * 2);
+
+            for (i, vertex) in chunk.iter().enumerate() {
+                let n = i * 2;
+                value_placeholders.push(format!("(${}, ${})", n + 1, n + 2));
+                params.push(&vertex.id);
+                params.push(&vertex.t.0);
+            }
+
+            let query = format!(
+                "INSERT INTO vertices (id, type) VALUES {} ON CONFLICT DO NOTHING RETURNING id",
+                value_placeholders.join(", ")
+            );
+
+            match trans.query(&query[..], &params[..]) {
+                Ok(rows) => inserted_ids.extend(rows.iter().map(|row| row.get(0))),
+                Err(err) => {
+                    trans.set_rollback();
+
+                    return match classify_db_error(&err) {
+                        Some(kind) => Err(kind.into()),
+                        None => Err(err.into()),
+                    };
+                }
+            }
+        }
+
+        trans.set_commit();
+        // Removing each id as it's reported means that if the same id
+        // appears more than once in `vertices`, only its first occurrence is
+        // `true`, the same as calling `create_vertex` for each in turn.
+        Ok(vertices.iter().map(|vertex| inserted_ids.remove(&vertex.id)).collect())
+    }
+
+    /// Creates multiple edges in a handful of round trips, via multi-valued
+    /// `INSERT`s, rather than one `INSERT` per edge.
+    ///
+    /// Returns a vector aligned with `keys`, containing the id of the edge
+    /// at that position - freshly generated if the edge didn't exist yet,
+    /// or its existing id if it was updated instead (the same upsert
+    /// semantics as `create_edge`, just batched).
+    pub fn create_edges(&self, keys: &[models::EdgeKey]) -> Result<Vec<Uuid>> {
+        if keys.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // Because this command could fail, we need to set a savepoint to
+        // roll back to, rather than spoiling the entire transaction
+        let trans = self.trans.savepoint("create_edges")?;
+
+        // `ON CONFLICT ... DO UPDATE` errors out if the same row would be
+        // affected twice in one statement, so a batch with two keys that
+        // share an (outbound_id, type, inbound_id) - e.g. the same edge
+        // appearing in two overlapping ingestion batches - has to be
+        // de-duplicated before it's sent. Last write wins, the same as
+        // calling `create_edge` twice in a row with the same key.
+        let mut deduped: Vec<&models::EdgeKey> = Vec::with_capacity(keys.len());
+        let mut deduped_index_by_key: HashMap<(Uuid, &str, Uuid), usize> = HashMap::with_capacity(keys.len());
+
+        for key in keys {
+            let dedup_key = (key.outbound_id, &key.t.0[..], key.inbound_id);
+
+            match deduped_index_by_key.get(&dedup_key) {
+                Some(&i) => deduped[i] = key,
+                None => {
+                    deduped_index_by_key.insert(dedup_key, deduped.len());
+                    deduped.push(key);
+                }
+            }
+        }
+
+        let ids: Vec<Uuid> = deduped.iter().map(|_| generate_uuid_v1()).collect();
+        let types: Vec<String> = deduped.iter().map(|key| key.t.0.clone()).collect();
+
+        // Re-aligned by key rather than trusting `RETURNING`'s row order to
+        // match the `VALUES` list, so a duplicate key in the original
+        // (non-deduplicated) input still gets the right id back.
+        let mut ids_by_key: HashMap<(Uuid, String, Uuid), Uuid> = HashMap::with_capacity(deduped.len());
+
+        // Postgres caps a single statement at 65535 bound parameters, so a
+        // batch larger than that has to be split across several `INSERT`s -
+        // four bound parameters per edge, hence the chunk size below.
+        for (chunk_index, chunk) in deduped.chunks(Self::MAX_BIND_PARAMS as usize / 4).enumerate() {
+            let offset = chunk_index * (Self::MAX_BIND_PARAMS as usize / 4);
+            let mut value_placeholders = Vec::with_capacity(chunk.len());
+            let mut params: Vec<&ToSql> = Vec::with_capacity(chunk.len() * 4);
+
+            for (i, key) in chunk.iter().enumerate() {
+                let n = i * 4;
+                value_placeholders.push(format!("(${}, ${}, ${}, ${}, CLOCK_TIMESTAMP())", n + 1, n + 2, n + 3, n + 4));
+                params.push(&ids[offset + i]);
+                params.push(&key.outbound_id);
+                params.push(&types[offset + i]);
+                params.push(&key.inbound_id);
+            }
+
+            let query = format!(
+                "
+                INSERT INTO edges (id, outbound_id, type, inbound_id, update_timestamp)
+                VALUES {}
+                ON CONFLICT ON CONSTRAINT edges_outbound_id_type_inbound_id_ukey
+                DO UPDATE SET update_timestamp=CLOCK_TIMESTAMP()
+                RETURNING id, outbound_id, type, inbound_id
+                ",
+                value_placeholders.join(", ")
+            );
+
+            match trans.query(&query[..], &params[..]) {
+                Ok(rows) => {
+                    for row in &rows {
+                        let id: Uuid = row.get(0);
+                        let outbound_id: Uuid = row.get(1);
+                        let t: String = row.get(2);
+                        let inbound_id: Uuid = row.get(3);
+                        ids_by_key.insert((outbound_id, t, inbound_id), id);
+                    }
+                }
+                Err(err) => {
+                    trans.set_rollback();
+
+                    return match classify_db_error(&err) {
+                        Some(kind) => Err(kind.into()),
+                        None => Err(err.into()),
+                    };
+                }
+            }
+        }
+
+        trans.set_commit();
+
+        Ok(keys.iter()
+            .map(|key| ids_by_key[&(key.outbound_id, key.t.0.clone(), key.inbound_id)])
+            .collect())
+    }
 }
 
 impl Transaction for PostgresTransaction {
@@ -234,12 +584,20 @@ impl Transaction for PostgresTransaction {
             &[&vertex.id, &vertex.t.0],
         );
 
-        if result.is_err() {
-            trans.set_rollback();
-            Ok(false)
-        } else {
-            trans.set_commit();
-            Ok(true)
+        match result {
+            Ok(_) => {
+                trans.set_commit();
+                Ok(true)
+            }
+            Err(err) => {
+                trans.set_rollback();
+
+                match classify_db_error(&err) {
+                    Some(ErrorKind::UniqueViolation) => Ok(false),
+                    Some(kind) => Err(kind.into()),
+                    None => Err(err.into()),
+                }
+            }
         }
     }
 
@@ -249,7 +607,7 @@ impl Transaction for PostgresTransaction {
         let (query, params) = sql_query_builder.into_query_payload("SELECT id, type FROM %t", vec![]);
         let params_refs: Vec<&ToSql> = params.iter().map(|x| &**x).collect();
 
-        let results = self.trans.query(&query[..], &params_refs[..])?;
+        let results = self.query_cached(&query[..], &params_refs[..])?;
         let mut vertices: Vec<models::Vertex> = Vec::new();
 
         for row in &results {
@@ -302,12 +660,20 @@ impl Transaction for PostgresTransaction {
             &[&id, &key.outbound_id, &key.t.0, &key.inbound_id],
         );
 
-        if results.is_err() {
-            trans.set_rollback();
-            Ok(false)
-        } else {
-            trans.set_commit();
-            Ok(true)
+        match results {
+            Ok(_) => {
+                trans.set_commit();
+                Ok(true)
+            }
+            Err(err) => {
+                trans.set_rollback();
+
+                match classify_db_error(&err) {
+                    Some(ErrorKind::UniqueViolation) => Ok(false),
+                    Some(kind) => Err(kind.into()),
+                    None => Err(err.into()),
+                }
+            }
         }
     }
 
@@ -320,7 +686,7 @@ impl Transaction for PostgresTransaction {
         );
         let params_refs: Vec<&ToSql> = params.iter().map(|x| &**x).collect();
 
-        let results = self.trans.query(&query[..], &params_refs[..])?;
+        let results = self.query_cached(&query[..], &params_refs[..])?;
         let mut edges: Vec<models::Edge> = Vec::new();
 
         for row in &results {
@@ -354,18 +720,20 @@ impl Transaction for PostgresTransaction {
         direction: models::EdgeDirection,
     ) -> Result<u64> {
         let results = match (direction, type_filter) {
-            (models::EdgeDirection::Outbound, Some(t)) => self.trans.query(
+            (models::EdgeDirection::Outbound, Some(t)) => self.query_cached(
                 "SELECT COUNT(*) FROM edges WHERE outbound_id=$1 AND type=$2",
                 &[&id, &t.0],
             ),
-            (models::EdgeDirection::Outbound, None) => self.trans
-                .query("SELECT COUNT(*) FROM edges WHERE outbound_id=$1", &[&id]),
-            (models::EdgeDirection::Inbound, Some(t)) => self.trans.query(
+            (models::EdgeDirection::Outbound, None) => {
+                self.query_cached("SELECT COUNT(*) FROM edges WHERE outbound_id=$1", &[&id])
+            }
+            (models::EdgeDirection::Inbound, Some(t)) => self.query_cached(
                 "SELECT COUNT(*) FROM edges WHERE inbound_id=$1 AND type=$2",
                 &[&id, &t.0],
             ),
-            (models::EdgeDirection::Inbound, None) => self.trans
-                .query("SELECT COUNT(*) FROM edges WHERE inbound_id=$1", &[&id]),
+            (models::EdgeDirection::Inbound, None) => {
+                self.query_cached("SELECT COUNT(*) FROM edges WHERE inbound_id=$1", &[&id])
+            }
         }?;
 
         for row in &results {
@@ -384,7 +752,7 @@ impl Transaction for PostgresTransaction {
             vec![Box::new(name.to_string())],
         );
         let params_refs: Vec<&ToSql> = params.iter().map(|x| &**x).collect();
-        let results = self.trans.query(&query[..], &params_refs[..])?;
+        let results = self.query_cached(&query[..], &params_refs[..])?;
         let mut metadata = Vec::new();
 
         for row in &results {
@@ -413,7 +781,7 @@ impl Transaction for PostgresTransaction {
             ],
         );
         let params_refs: Vec<&ToSql> = params.iter().map(|x| &**x).collect();
-        self.trans.execute(&query[..], &params_refs[..])?;
+        self.execute_cached(&query[..], &params_refs[..])?;
         Ok(())
     }
 
@@ -443,7 +811,7 @@ impl Transaction for PostgresTransaction {
         );
 
         let params_refs: Vec<&ToSql> = params.iter().map(|x| &**x).collect();
-        let results = self.trans.query(&query[..], &params_refs[..])?;
+        let results = self.query_cached(&query[..], &params_refs[..])?;
         let mut metadata = Vec::new();
 
         for row in &results {
@@ -476,7 +844,7 @@ impl Transaction for PostgresTransaction {
             ],
         );
         let params_refs: Vec<&ToSql> = params.iter().map(|x| &**x).collect();
-        self.trans.execute(&query[..], &params_refs[..])?;
+        self.execute_cached(&query[..], &params_refs[..])?;
         Ok(())
     }
 
@@ -492,3 +860,56 @@ impl Transaction for PostgresTransaction {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::PostgresTlsMode;
+
+    #[test]
+    fn from_connection_string_reads_sslmode_from_a_uri() {
+        let cases = [
+            ("postgres://user@host/db?sslmode=disable", PostgresTlsMode::None),
+            ("postgres://user@host/db?sslmode=allow", PostgresTlsMode::Prefer),
+            ("postgres://user@host/db?sslmode=prefer", PostgresTlsMode::Prefer),
+            ("postgres://user@host/db?sslmode=require", PostgresTlsMode::Require),
+            ("postgres://user@host/db?sslmode=verify-ca", PostgresTlsMode::Require),
+            ("postgres://user@host/db?sslmode=verify-full", PostgresTlsMode::Require),
+            (
+                "postgres://user@host/db?connect_timeout=10&sslmode=require",
+                PostgresTlsMode::Require,
+            ),
+            ("postgres://user@host/db", PostgresTlsMode::Prefer),
+        ];
+
+        for (connection_string, expected) in &cases {
+            assert_eq!(
+                PostgresTlsMode::from_connection_string(connection_string),
+                *expected,
+                "connection string: {}",
+                connection_string
+            );
+        }
+    }
+
+    #[test]
+    fn from_connection_string_reads_sslmode_from_a_keyword_value_dsn() {
+        let cases = [
+            ("host=localhost dbname=db sslmode=disable", PostgresTlsMode::None),
+            ("host=localhost dbname=db sslmode=require", PostgresTlsMode::Require),
+            (
+                "host=localhost dbname=db sslmode='require'",
+                PostgresTlsMode::Require,
+            ),
+            ("host=localhost dbname=db", PostgresTlsMode::Prefer),
+        ];
+
+        for (connection_string, expected) in &cases {
+            assert_eq!(
+                PostgresTlsMode::from_connection_string(connection_string),
+                *expected,
+                "connection string: {}",
+                connection_string
+            );
+        }
+    }
+}
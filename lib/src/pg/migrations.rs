@@ -0,0 +1,73 @@
+use super::schema;
+use errors::Result;
+use postgres::Connection;
+
+/// A single schema migration: a monotonically increasing version number,
+/// and the SQL that brings the schema from `version - 1` up to `version`.
+struct Migration {
+    version: i32,
+    up_sql: &'static str,
+}
+
+/// Every migration IndraDB knows about, in the order they must be applied.
+/// Adding a new migration means appending a new entry here with the next
+/// version number - existing entries must never be changed once released,
+/// since they may already have been applied against a live database.
+const MIGRATIONS: &'static [Migration] = &[
+    Migration {
+        version: 1,
+        up_sql: schema::SCHEMA,
+    },
+];
+
+// An arbitrary, fixed key for the advisory lock, so that every IndraDB
+// process migrating the same database contends on the same lock rather
+// than locking per-table or per-row.
+const ADVISORY_LOCK_KEY: i64 = 0x696e_6472_6164_62; // "indradb"
+
+const BOOKKEEPING_TABLE_SQL: &'static str = "
+    CREATE TABLE IF NOT EXISTS schema_migrations (
+        version INT PRIMARY KEY,
+        applied_at TIMESTAMPTZ NOT NULL DEFAULT CLOCK_TIMESTAMP()
+    )
+";
+
+/// Brings the schema up to date, applying every migration with a version
+/// greater than the highest version already recorded in
+/// `schema_migrations`, in order, inside a single transaction.
+///
+/// This is safe to call repeatedly, including against a database that
+/// already has data in it - each migration step is only ever applied once.
+/// An advisory lock held for the duration of the transaction keeps
+/// concurrent IndraDB processes from racing to migrate the same database.
+pub fn run(conn: &Connection) -> Result<()> {
+    let trans = conn.transaction()?;
+
+    trans.execute("SELECT pg_advisory_xact_lock($1)", &[&ADVISORY_LOCK_KEY])?;
+    trans.execute(BOOKKEEPING_TABLE_SQL, &[])?;
+
+    let max_version: i32 = {
+        let results = trans.query("SELECT COALESCE(MAX(version), 0) FROM schema_migrations", &[])?;
+        results.get(0).get(0)
+    };
+
+    for migration in MIGRATIONS {
+        if migration.version > max_version {
+            // `batch_execute` runs `up_sql` through the simple query
+            // protocol, which accepts an arbitrary number of
+            // semicolon-separated statements in one call - unlike
+            // `execute`/`query`, it doesn't require splitting the SQL
+            // ourselves, so a statement with a `;` inside a string literal
+            // or a multi-statement function body isn't corrupted.
+            trans.batch_execute(migration.up_sql)?;
+
+            trans.execute(
+                "INSERT INTO schema_migrations (version, applied_at) VALUES ($1, CLOCK_TIMESTAMP())",
+                &[&migration.version],
+            )?;
+        }
+    }
+
+    trans.set_commit();
+    Ok(())
+}